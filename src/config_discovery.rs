@@ -0,0 +1,192 @@
+//! Hierarchical `treefmt.toml` discovery and merging is in this module: a nested config's
+//! formatters override or extend the nearest ancestor config's, so subprojects can declare their
+//! own formatters without a monolithic root file.
+
+use crate::config::{self, ProjectConfig};
+use crate::formatter::{Formatter, FormatterName};
+use crate::CLOG;
+use ignore::WalkBuilder;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Directories whose contents are never descended into while discovering nested configs.
+const BOUNDARY_DIRS: &[&str] = &["node_modules", "target", ".git", ".hg", ".jj"];
+
+/// A directory that has its own `treefmt.toml`, together with that config merged over its
+/// nearest ancestor's.
+struct ConfigScope {
+    dir: PathBuf,
+    config: ProjectConfig,
+    /// Formatter names this scope's own `treefmt.toml` defines, as opposed to ones it merely
+    /// inherited from its parent -- only these need their own cache-qualified `Formatter`.
+    local_names: BTreeSet<String>,
+}
+
+/// Whether `path` (rooted at `tree_root`) is inside a directory discovery should never descend
+/// into. Only components *under* `tree_root` are considered, so a checkout that itself happens
+/// to live under a directory named e.g. `target` isn't treated as one big boundary.
+pub(crate) fn is_boundary(tree_root: &Path, path: &Path) -> bool {
+    path.strip_prefix(tree_root)
+        .unwrap_or(path)
+        .components()
+        .any(|c| BOUNDARY_DIRS.contains(&c.as_os_str().to_string_lossy().as_ref()))
+}
+
+/// Discover every nested `treefmt.toml` under `tree_root` (other than `root_treefmt_toml` itself),
+/// merging each one over its nearest ancestor's effective config.
+fn discover_scopes(
+    tree_root: &Path,
+    root_treefmt_toml: &Path,
+    root_config: &ProjectConfig,
+) -> Vec<ConfigScope> {
+    let mut nested_toml_dirs: Vec<PathBuf> = WalkBuilder::new(tree_root)
+        .filter_entry(|entry| !is_boundary(tree_root, entry.path()))
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name() == "treefmt.toml" && entry.path() != root_treefmt_toml)
+        .filter_map(|entry| entry.path().parent().map(Path::to_path_buf))
+        .collect();
+
+    // Shallowest first, so each scope's parent (if any) has already been resolved by the time we
+    // get to it.
+    nested_toml_dirs.sort_by_key(|dir| dir.components().count());
+
+    let mut scopes: Vec<ConfigScope> = vec![];
+    for dir in nested_toml_dirs {
+        let child = match config::from_path(&dir.join("treefmt.toml")) {
+            Ok(config) => config,
+            Err(err) => {
+                CLOG.error(&format!(
+                    "Ignoring nested treefmt.toml at {}: {}",
+                    dir.display(),
+                    err
+                ));
+                continue;
+            }
+        };
+
+        let parent_config = nearest_ancestor(&scopes, &dir)
+            .map(|scope| &scope.config)
+            .unwrap_or(root_config);
+
+        let mut formatter = parent_config.formatter.clone();
+        formatter.extend(child.formatter.clone());
+
+        scopes.push(ConfigScope {
+            dir,
+            config: ProjectConfig { formatter },
+            local_names: child.formatter.keys().cloned().collect(),
+        });
+    }
+
+    scopes
+}
+
+fn nearest_ancestor<'a>(scopes: &'a [ConfigScope], dir: &Path) -> Option<&'a ConfigScope> {
+    scopes
+        .iter()
+        .filter(|scope| dir != scope.dir && dir.starts_with(&scope.dir))
+        .max_by_key(|scope| scope.dir.components().count())
+}
+
+/// Build a `FormatterName -> Formatter` map per discovered scope directory (plus the root).
+///
+/// Reuses the nearest ancestor scope's (or the root's) `Formatter` instances for any name a scope
+/// doesn't itself override, so files in different directories that share an *unmodified*
+/// formatter still land in the same cache bucket. Only the names a nested `treefmt.toml` actually
+/// defines are rebuilt and namespaced by scope directory, so they invalidate independently of the
+/// root's cache entries.
+pub(crate) fn resolve_formatters(
+    tree_root: &Path,
+    treefmt_toml: &Path,
+    root_config: &ProjectConfig,
+    root_formatters: &BTreeMap<FormatterName, Formatter>,
+) -> BTreeMap<PathBuf, BTreeMap<FormatterName, Formatter>> {
+    let scopes = discover_scopes(tree_root, treefmt_toml, root_config);
+
+    let mut by_dir: BTreeMap<PathBuf, BTreeMap<FormatterName, Formatter>> = BTreeMap::new();
+    by_dir.insert(tree_root.to_path_buf(), root_formatters.clone());
+
+    for scope in &scopes {
+        // `scopes` is shallowest first, so the nearest ancestor scope is already in `by_dir`.
+        let mut formatters = nearest_ancestor(&scopes, &scope.dir)
+            .and_then(|parent| by_dir.get(&parent.dir))
+            .unwrap_or(root_formatters)
+            .clone();
+
+        for name in &scope.local_names {
+            // unwrap: local_names comes directly from scope.config.formatter's keys
+            let fmt_config = scope.config.formatter.get(name).unwrap();
+            match Formatter::from_config(tree_root, name, fmt_config) {
+                Ok(mut fmt) => {
+                    // This scope re-declares `name`, so it's no longer the inherited formatter
+                    // (unqualified, or qualified by an ancestor scope) -- drop that entry or a
+                    // file here would be formatted by both the parent's and this scope's config.
+                    let suffix = format!("::{}", name);
+                    formatters.retain(|key, _| {
+                        let key = key.to_string();
+                        key != *name && !key.ends_with(&suffix)
+                    });
+                    let qualified =
+                        FormatterName::from(format!("{}::{}", scope.dir.display(), name));
+                    // Matches are keyed by the formatter's own name, so it carries the
+                    // qualified one too.
+                    fmt.name = qualified.clone();
+                    formatters.insert(qualified, fmt);
+                }
+                Err(err) => CLOG.error(&format!(
+                    "Ignoring formatter #{} in {}: {}",
+                    name,
+                    scope.dir.display(),
+                    err
+                )),
+            }
+        }
+
+        by_dir.insert(scope.dir.clone(), formatters);
+    }
+
+    by_dir
+}
+
+/// Pick the effective formatters map for `path`: the deepest scope directory from `by_dir` that
+/// is an ancestor of `path`.
+pub(crate) fn formatters_for<'a>(
+    by_dir: &'a BTreeMap<PathBuf, BTreeMap<FormatterName, Formatter>>,
+    path: &Path,
+) -> &'a BTreeMap<FormatterName, Formatter> {
+    by_dir
+        .iter()
+        .filter(|(dir, _)| path.starts_with(dir))
+        .max_by_key(|(dir, _)| dir.components().count())
+        .map(|(_, formatters)| formatters)
+        .expect("the root scope is always present in `by_dir`")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_boundary_ignores_components_above_tree_root() {
+        let tree_root = Path::new("/home/ci/target/checkout");
+        assert!(!is_boundary(
+            tree_root,
+            &tree_root.join("src/main.rs")
+        ));
+    }
+
+    #[test]
+    fn is_boundary_matches_nested_boundary_dirs() {
+        let tree_root = Path::new("/home/ci/checkout");
+        assert!(is_boundary(
+            tree_root,
+            &tree_root.join("frontend/node_modules/left-pad/index.js")
+        ));
+        assert!(is_boundary(
+            tree_root,
+            &tree_root.join("backend/target/debug/build")
+        ));
+        assert!(!is_boundary(tree_root, &tree_root.join("src/main.rs")));
+    }
+}
@@ -1,15 +1,19 @@
 //! The main formatting engine logic is in this module.
 
-use crate::{config, eval_cache::CacheManifest, formatter::FormatterName, CLOG};
+use crate::{
+    config, config_discovery, eval_cache::CacheManifest, fingerprint, formatter::FormatterName,
+    git, CLOG,
+};
 use crate::{expand_path, formatter::Formatter, get_meta_mtime, get_path_mtime, Mtime};
 use anyhow::anyhow;
 use ignore::WalkBuilder;
 use rayon::prelude::*;
 use std::iter::Iterator;
 use std::path::{Path, PathBuf};
-use std::{collections::BTreeMap, time::Instant};
+use std::{collections::BTreeMap, fs, time::Instant};
 
 /// Run the treefmt
+#[allow(clippy::too_many_arguments)]
 pub fn run_treefmt(
     tree_root: &Path,
     work_dir: &Path,
@@ -18,6 +22,9 @@ pub fn run_treefmt(
     paths: &[PathBuf],
     clear_cache: bool,
     fail_on_change: bool,
+    changed_since: Option<&str>,
+    check: bool,
+    jobs: usize,
 ) -> anyhow::Result<()> {
     assert!(tree_root.is_absolute());
     assert!(work_dir.is_absolute());
@@ -62,29 +69,11 @@ pub fn run_treefmt(
         return Ok(());
     }
 
-    // Load the treefmt.toml file
-    let project_config = config::from_path(&treefmt_toml)?;
-
-    timed_debug("load config");
-
-    // Load all the formatter instances from the config. Ignore the ones that failed.
-    let formatters =
-        project_config
-            .formatter
-            .iter()
-            .fold(BTreeMap::new(), |mut sum, (name, fmt_config)| {
-                match Formatter::from_config(&tree_root, &name, &fmt_config) {
-                    Ok(fmt_matcher) => {
-                        sum.insert(fmt_matcher.name.clone(), fmt_matcher);
-                    }
-                    Err(err) => CLOG.error(&format!(
-                        "Ignoring formatter #{} due to error: {}",
-                        name, err
-                    )),
-                };
-                sum
-            });
-
+    // Load the root config, every formatter it and its nested configs define, and resolve a
+    // formatters map per config scope. Shared with [`crate::watch`], so `--watch` honors nested
+    // `treefmt.toml`s the same way a one-shot run does.
+    let (formatters_by_scope, all_formatters) =
+        load_formatters_by_scope(&tree_root, &treefmt_toml)?;
     timed_debug("load formatters");
 
     // Load the eval cache
@@ -96,21 +85,68 @@ pub fn run_treefmt(
     };
     timed_debug("load cache");
     // Insert the new formatter configs
-    let cache = cache.update_formatters(formatters.clone());
+    let cache = cache.update_formatters(all_formatters.clone());
+
+    // Compute a version fingerprint for each formatter and compare it to the ones recorded on
+    // the previous run. A formatter whose binary was upgraded in place gets its cache entries
+    // treated as a miss below, rather than requiring a manual `--clear-cache`.
+    let old_fingerprints = fingerprint::load(cache_dir);
+    let new_fingerprints = all_formatters
+        .iter()
+        .map(|(name, fmt)| (name.clone(), fingerprint::compute(fmt)))
+        .collect::<BTreeMap<_, _>>();
+    let stale_formatters = new_fingerprints
+        .iter()
+        .filter(|(name, fp)| old_fingerprints.get(*name) != Some(*fp))
+        .map(|(name, _)| name.clone())
+        .collect::<std::collections::BTreeSet<_>>();
+    timed_debug("fingerprint formatters");
 
-    // Configure the tree walker
-    let walker = {
+    // In `--changed-since` mode, restrict matching to the set of files git reports as
+    // added/modified relative to the given revision (or the merge-base with a default branch).
+    // Fall back to a full walk when the tree isn't a git checkout or the git invocation fails.
+    let changed_files = changed_since.and_then(|rev| {
+        let rev = if rev.is_empty() { None } else { Some(rev) };
+        match git::get_git_modified_files(tree_root, rev) {
+            Some(files) => Some(files),
+            None => {
+                CLOG.warn(&"--changed-since: not a git checkout (or git failed), falling back to a full walk".to_string());
+                None
+            }
+        }
+    });
+    timed_debug("changed-since");
+
+    // Determine the walker roots. In `--changed-since` mode, seed the walker directly from the
+    // changed files themselves (intersected with the requested paths) instead of `paths`, so the
+    // walk visits only the diff rather than the whole tree before discarding everything else.
+    let walker_roots: Vec<PathBuf> = match &changed_files {
+        Some(changed) => changed
+            .iter()
+            .filter(|changed_path| paths.iter().any(|root| changed_path.starts_with(root)))
+            .cloned()
+            .collect(),
+        None => paths.clone(),
+    };
+
+    // Configure the tree walker. `None` when `--changed-since` found nothing to do under the
+    // requested paths, since `WalkBuilder` needs at least one root.
+    let walker = if walker_roots.is_empty() {
+        None
+    } else {
         // For some reason the WalkBuilder must start with one path, but can add more paths later.
-        // unwrap: we checked before that there is at least one path in the vector
-        let mut builder = WalkBuilder::new(paths.first().unwrap());
+        // unwrap: we just checked that walker_roots is non-empty
+        let mut builder = WalkBuilder::new(walker_roots.first().unwrap());
+        // Never descend into boundary directories (node_modules, target, ...) while walking.
+        builder.filter_entry(|entry| !config_discovery::is_boundary(tree_root, entry.path()));
         // Add the other paths
-        for path in paths[1..].iter() {
+        for path in walker_roots[1..].iter() {
             builder.add(path);
         }
         // TODO: builder has a lot of interesting options.
         // TODO: use build_parallel with a Visitor.
         //       See https://docs.rs/ignore/0.4.17/ignore/struct.WalkParallel.html#method.visit
-        builder.build()
+        Some(builder.build())
     };
 
     // Start a collection of formatter names to path to mtime
@@ -118,7 +154,7 @@ pub fn run_treefmt(
 
     // Now traverse the filesystem and classify each file. We also want the file mtime to see if it changed
     // afterwards.
-    for walk_entry in walker {
+    for walk_entry in walker.into_iter().flatten() {
         match walk_entry {
             Ok(dir_entry) => {
                 if let Some(file_type) = dir_entry.file_type() {
@@ -127,8 +163,13 @@ pub fn run_treefmt(
                         traversed_files += 1;
 
                         let path = dir_entry.path().to_path_buf();
+
+                        // Resolve the formatters in effect for this path's config scope (the
+                        // nearest ancestor directory with its own `treefmt.toml`, or the root).
+                        let scoped_formatters = config_discovery::formatters_for(&formatters_by_scope, &path);
+
                         // FIXME: complain if multiple matchers match the same path.
-                        for (_, fmt) in formatters.clone() {
+                        for (_, fmt) in scoped_formatters.clone() {
                             if fmt.clone().is_match(&path) {
                                 // Keep track of how many files were associated with a formatter
                                 matched_files += 1;
@@ -158,96 +199,66 @@ pub fn run_treefmt(
     timed_debug("tree walk");
 
     // Filter out all of the paths that were already in the cache
-    let matches = cache.clone().filter_matches(matches);
+    let filtered = cache.clone().filter_matches(matches.clone());
+
+    // A formatter whose version fingerprint changed since the last run is treated as a full
+    // cache miss, regardless of what the mtime-keyed cache says: force its un-filtered matches
+    // back in.
+    let matches = filtered
+        .into_iter()
+        .map(|(name, paths)| {
+            if stale_formatters.contains(&name) {
+                (name.clone(), matches.get(&name).cloned().unwrap_or_default())
+            } else {
+                (name, paths)
+            }
+        })
+        .collect::<BTreeMap<FormatterName, BTreeMap<PathBuf, Mtime>>>();
 
     timed_debug("filter_matches");
 
     // Keep track of the paths that are actually going to be formatted
     filtered_files = matches.values().map(|x| x.len()).sum();
 
-    // Now run all the formatters and collect the formatted paths.
-    let new_matches = matches
-        .par_iter()
-        .map(|(formatter_name, path_mtime)| {
-            let paths: Vec<PathBuf> = path_mtime.keys().cloned().collect();
-            // unwrap: the key exists since matches was built from that previous collection
-            let formatter = formatters.get(&formatter_name).unwrap();
-
-            // Don't run the formatter if there are no paths to format!
-            if paths.is_empty() {
-                (formatter_name.clone(), path_mtime.clone())
-            } else {
-                let start_time = Instant::now();
-
-                match formatter.clone().fmt(&paths) {
-                    // FIXME: do we care about the output?
-                    Ok(_) => {
-                        CLOG.info(&format!(
-                            "{}: {} files processed in {:.2?}",
-                            formatter.name,
-                            paths.len(),
-                            start_time.elapsed()
-                        ));
-
-                        // Get the new mtimes and compare them to the original ones
-                        let new_paths = paths.into_iter().fold(BTreeMap::new(), |mut sum, path| {
-                            // unwrap: assume that the file still exists after formatting
-                            let mtime = get_path_mtime(&path).unwrap();
-                            sum.insert(path, mtime);
-                            sum
-                        });
-                        // Return the new mtimes
-                        (formatter_name.clone(), new_paths)
-                    }
-                    Err(err) => {
-                        // FIXME: What is the right behaviour if a formatter has failed running?
-                        CLOG.error(&format!("{} failed: {}", &formatter, err));
-                        // Assume the paths were not formatted
-                        (formatter_name.clone(), path_mtime.clone())
-                    }
-                }
+    // Built once and shared by whichever branch below runs the formatters, rather than rebuilt
+    // per call.
+    let pool = build_thread_pool(jobs);
+
+    if check {
+        // Read-only check mode: format a scratch copy of each matched file and compare it to the
+        // original instead of overwriting it. The originals and the mtime cache are left as they
+        // are; the scratch copies are written beside the originals and removed afterwards.
+        let would_change = check_matches(&all_formatters, &matches, &pool)?;
+
+        for (name, paths) in &would_change {
+            reformatted_files += paths.len();
+            CLOG.warn(&format!(
+                "{}: {} file(s) would be reformatted",
+                name,
+                paths.len()
+            ));
+            for path in paths {
+                CLOG.warn(&format!("  {}", path.display()));
             }
-        })
-        .collect::<BTreeMap<FormatterName, BTreeMap<PathBuf, Mtime>>>();
-    timed_debug("format");
-
-    // Record the new matches in the cache
-    let cache = cache.add_results(new_matches.clone());
-    // And write to disk
-    cache.write(cache_dir, treefmt_toml);
-    timed_debug("write cache");
-
-    // Diff the old matches with the new matches
-    let changed_matches: BTreeMap<FormatterName, Vec<PathBuf>> =
-        new_matches
-            .into_iter()
-            .fold(BTreeMap::new(), |mut sum, (name, new_paths)| {
-                // unwrap: we know that the name exists
-                let old_paths = matches.get(&name).unwrap().clone();
-                let filtered = new_paths
-                    .iter()
-                    .filter_map(|(k, v)| {
-                        // unwrap: we know that the key exists
-                        if old_paths.get(k).unwrap() == v {
-                            None
-                        } else {
-                            Some(k.clone())
-                        }
-                    })
-                    .collect();
+        }
+        timed_debug("check");
+    } else {
+        // Now run all the formatters and collect the formatted paths.
+        let new_matches = run_formatters(&all_formatters, &matches, &pool);
+        timed_debug("format");
 
-                sum.insert(name, filtered);
-                sum
-            });
+        // Record the new matches in the cache
+        let cache = cache.add_results(new_matches.clone());
+        // And write to disk
+        cache.write(cache_dir, treefmt_toml);
+        fingerprint::write(cache_dir, &new_fingerprints);
+        timed_debug("write cache");
 
-    // Finally display all the paths that have been formatted
-    for (_name, paths) in changed_matches {
-        // Keep track of how many files were reformatted
-        reformatted_files += paths.len();
-        // println!("{}:", name);
-        // for path in paths {
-        //     println!("- {}", path.display());
-        // }
+        // Diff the old matches with the new matches, and keep track of how many were reformatted
+        reformatted_files += diff_matches(&matches, &new_matches)
+            .values()
+            .map(|paths| paths.len())
+            .sum::<usize>();
     }
 
     println!(
@@ -265,10 +276,342 @@ all of this in {:.2?}
         start_time.elapsed()
     );
 
-    // Fail if --fail-on-change was passed.
-    if reformatted_files > 0 && fail_on_change {
-        return Err(anyhow!("fail-on-change"));
+    // Fail if --fail-on-change was passed, or if --check found files that would be reformatted.
+    if reformatted_files > 0 && (fail_on_change || check) {
+        return Err(anyhow!(if check {
+            "check-failed"
+        } else {
+            "fail-on-change"
+        }));
     }
 
     Ok(())
 }
+
+/// Load the root `treefmt.toml`, build its formatters, and resolve every nested scope's
+/// formatters over it.
+///
+/// Shared by [`run_treefmt`] and [`crate::watch`] so both honor nested configs the same way --
+/// a file under a subproject with its own `treefmt.toml` uses that config's (merged-over-root)
+/// formatters instead of the root's, regardless of which one resolved it.
+pub(crate) fn load_formatters_by_scope(
+    tree_root: &Path,
+    treefmt_toml: &Path,
+) -> anyhow::Result<(
+    BTreeMap<PathBuf, BTreeMap<FormatterName, Formatter>>,
+    BTreeMap<FormatterName, Formatter>,
+)> {
+    let project_config = config::from_path(treefmt_toml)?;
+
+    // Load all the formatter instances from the config. Ignore the ones that failed.
+    let formatters =
+        project_config
+            .formatter
+            .iter()
+            .fold(BTreeMap::new(), |mut sum, (name, fmt_config)| {
+                match Formatter::from_config(tree_root, name, fmt_config) {
+                    Ok(fmt_matcher) => {
+                        sum.insert(fmt_matcher.name.clone(), fmt_matcher);
+                    }
+                    Err(err) => CLOG.error(&format!(
+                        "Ignoring formatter #{} due to error: {}",
+                        name, err
+                    )),
+                };
+                sum
+            });
+
+    // Discover any nested `treefmt.toml` files and resolve a formatters map per config scope, so
+    // a file under a subproject with its own config uses that config's (merged-over-root)
+    // formatters instead of the root's.
+    let formatters_by_scope =
+        config_discovery::resolve_formatters(tree_root, treefmt_toml, &project_config, &formatters);
+    // The union of every scope's formatters, used for cache keying and for actually running them
+    // -- scope-qualified names and root names alike end up as distinct `FormatterName`s here.
+    // Matching a path against a formatter still goes through `formatters_by_scope`, so a
+    // subproject's formatters are only ever considered for paths under that subproject.
+    let all_formatters: BTreeMap<FormatterName, Formatter> = formatters_by_scope
+        .values()
+        .flat_map(|scope_formatters| scope_formatters.clone())
+        .collect();
+
+    Ok((formatters_by_scope, all_formatters))
+}
+
+/// Resolve the mtime-tagged matches for a single path against the formatters in scope for it.
+///
+/// Used by [`crate::watch`] to turn a handful of dirty paths from an fsmonitor event into the
+/// same `(FormatterName, PathBuf, Mtime)` shape the tree walk in [`run_treefmt`] produces, without
+/// reconstructing the whole `matches` map. Goes through [`config_discovery::formatters_for`] so a
+/// path under a subproject's nested config is only matched against that scope's formatters, the
+/// same way [`run_treefmt`]'s tree walk resolves it.
+pub(crate) fn match_path(
+    formatters_by_scope: &BTreeMap<PathBuf, BTreeMap<FormatterName, Formatter>>,
+    path: &Path,
+) -> BTreeMap<FormatterName, Mtime> {
+    let mut out = BTreeMap::new();
+    if let Ok(mtime) = get_path_mtime(path) {
+        let formatters = config_discovery::formatters_for(formatters_by_scope, path);
+        for (_, fmt) in formatters.clone() {
+            if fmt.clone().is_match(path) {
+                out.insert(fmt.name, mtime);
+            }
+        }
+    }
+    out
+}
+
+/// Maximum number of paths handed to a single formatter invocation, chosen to stay comfortably
+/// under typical OS argument-length limits (`ARG_MAX`) regardless of how many files one
+/// formatter matched.
+const MAX_CHUNK_SIZE: usize = 1000;
+
+/// Build the bounded-concurrency thread pool [`run_formatters`] schedules chunks on.
+///
+/// Callers that invoke [`run_formatters`] repeatedly (like [`crate::watch`], once per debounced
+/// batch) should build this once and reuse it, rather than paying rayon's pool-construction cost
+/// on every call.
+pub(crate) fn build_thread_pool(jobs: usize) -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.max(1))
+        .build()
+        .unwrap_or_else(|err| {
+            CLOG.warn(&format!(
+                "Couldn't build a {}-job thread pool ({}), falling back to rayon's default",
+                jobs, err
+            ));
+            rayon::ThreadPoolBuilder::new()
+                .build()
+                .expect("rayon's default thread pool should always build")
+        })
+}
+
+/// Run every formatter over its matched paths and collect the resulting mtimes.
+///
+/// Factored out of [`run_treefmt`] so long-running consumers (like [`crate::watch`]) can re-run
+/// it against a small, lazily-resolved set of matches on every filesystem event instead of
+/// re-walking and re-matching the whole tree.
+///
+/// Rather than one blocking subprocess per formatter (which lets a single formatter with tens of
+/// thousands of matched files monopolize a core while the rest of the machine idles), each
+/// formatter's path list is split into `MAX_CHUNK_SIZE`-sized chunks and the chunks across every
+/// formatter are scheduled as a flat pool of jobs bounded to `pool`'s concurrency.
+pub(crate) fn run_formatters(
+    formatters: &BTreeMap<FormatterName, Formatter>,
+    matches: &BTreeMap<FormatterName, BTreeMap<PathBuf, Mtime>>,
+    pool: &rayon::ThreadPool,
+) -> BTreeMap<FormatterName, BTreeMap<PathBuf, Mtime>> {
+    let chunks: Vec<(FormatterName, BTreeMap<PathBuf, Mtime>)> = matches
+        .iter()
+        .flat_map(|(name, path_mtime)| {
+            let keys: Vec<PathBuf> = path_mtime.keys().cloned().collect();
+            keys.chunks(MAX_CHUNK_SIZE.max(1))
+                .map(|chunk| {
+                    let subset = chunk
+                        .iter()
+                        .map(|path| (path.clone(), path_mtime[path].clone()))
+                        .collect::<BTreeMap<_, _>>();
+                    (name.clone(), subset)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let results: Vec<(FormatterName, BTreeMap<PathBuf, Mtime>)> = pool.install(|| {
+        chunks
+            .par_iter()
+            .map(|(formatter_name, chunk)| {
+                // unwrap: the key exists since matches was built from that previous collection
+                let formatter = formatters.get(formatter_name).unwrap();
+                run_chunk(formatter, chunk)
+            })
+            .collect()
+    });
+
+    // Merge the per-chunk mtimes back together per formatter, making sure every formatter from
+    // `matches` ends up with an entry even if it matched no paths.
+    let mut merged: BTreeMap<FormatterName, BTreeMap<PathBuf, Mtime>> = matches
+        .keys()
+        .map(|name| (name.clone(), BTreeMap::new()))
+        .collect();
+    for (name, paths) in results {
+        merged.entry(name).or_default().extend(paths);
+    }
+    merged
+}
+
+/// Format a single chunk of one formatter's matched paths, falling back to the chunk's original
+/// mtimes (i.e. treating it as unchanged) if the formatter invocation fails.
+fn run_chunk(
+    formatter: &Formatter,
+    chunk: &BTreeMap<PathBuf, Mtime>,
+) -> (FormatterName, BTreeMap<PathBuf, Mtime>) {
+    let paths: Vec<PathBuf> = chunk.keys().cloned().collect();
+
+    // Don't run the formatter if there are no paths to format!
+    if paths.is_empty() {
+        return (formatter.name.clone(), BTreeMap::new());
+    }
+
+    let start_time = Instant::now();
+
+    match formatter.clone().fmt(&paths) {
+        // FIXME: do we care about the output?
+        Ok(_) => {
+            CLOG.info(&format!(
+                "{}: {} files processed in {:.2?}",
+                formatter.name,
+                paths.len(),
+                start_time.elapsed()
+            ));
+
+            // Get the new mtimes and compare them to the original ones
+            let new_paths = paths.into_iter().fold(BTreeMap::new(), |mut sum, path| {
+                // unwrap: assume that the file still exists after formatting
+                let mtime = get_path_mtime(&path).unwrap();
+                sum.insert(path, mtime);
+                sum
+            });
+            (formatter.name.clone(), new_paths)
+        }
+        Err(err) => {
+            // FIXME: What is the right behaviour if a formatter has failed running?
+            CLOG.error(&format!("{} failed: {}", &formatter, err));
+            // Assume the paths were not formatted
+            (formatter.name.clone(), chunk.clone())
+        }
+    }
+}
+
+/// Diff an old formatter-name -> path -> mtime map against a new one, returning the paths whose
+/// mtime changed (i.e. that were actually reformatted).
+pub(crate) fn diff_matches(
+    old_matches: &BTreeMap<FormatterName, BTreeMap<PathBuf, Mtime>>,
+    new_matches: &BTreeMap<FormatterName, BTreeMap<PathBuf, Mtime>>,
+) -> BTreeMap<FormatterName, Vec<PathBuf>> {
+    new_matches
+        .iter()
+        .fold(BTreeMap::new(), |mut sum, (name, new_paths)| {
+            // unwrap: we know that the name exists
+            let old_paths = old_matches.get(name).unwrap();
+            let changed = new_paths
+                .iter()
+                .filter_map(|(k, v)| {
+                    // unwrap: we know that the key exists
+                    if old_paths.get(k).unwrap() == v {
+                        None
+                    } else {
+                        Some(k.clone())
+                    }
+                })
+                .collect();
+
+            sum.insert(name.clone(), changed);
+            sum
+        })
+}
+
+/// Format a scratch copy of every matched file and report the ones whose contents would change,
+/// without touching the originals.
+///
+/// Chunked and scheduled on `pool` the same way [`run_formatters`] runs a real pass, rather than
+/// one unbounded `fmt` call per formatter -- a matched-file list that would otherwise blow past
+/// `ARG_MAX`, or run serially where a normal run would parallelize, gets the same treatment here.
+fn check_matches(
+    formatters: &BTreeMap<FormatterName, Formatter>,
+    matches: &BTreeMap<FormatterName, BTreeMap<PathBuf, Mtime>>,
+    pool: &rayon::ThreadPool,
+) -> anyhow::Result<BTreeMap<FormatterName, Vec<PathBuf>>> {
+    let chunks: Vec<(FormatterName, Vec<PathBuf>)> = matches
+        .iter()
+        .flat_map(|(name, path_mtime)| {
+            let keys: Vec<PathBuf> = path_mtime.keys().cloned().collect();
+            keys.chunks(MAX_CHUNK_SIZE.max(1))
+                .map(|chunk| (name.clone(), chunk.to_vec()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let results: Vec<anyhow::Result<(FormatterName, Vec<PathBuf>)>> = pool.install(|| {
+        chunks
+            .par_iter()
+            .map(|(formatter_name, paths)| {
+                // unwrap: the key exists since matches was built from that previous collection
+                let formatter = formatters.get(formatter_name).unwrap();
+                check_chunk(formatter_name, formatter, paths)
+            })
+            .collect()
+    });
+
+    let mut would_change: BTreeMap<FormatterName, Vec<PathBuf>> = BTreeMap::new();
+    for result in results {
+        let (formatter_name, changed) = result?;
+        if !changed.is_empty() {
+            would_change
+                .entry(formatter_name)
+                .or_default()
+                .extend(changed);
+        }
+    }
+
+    Ok(would_change)
+}
+
+/// Format a scratch copy of a chunk of one formatter's matched paths and report which ones would
+/// change, without touching the originals.
+fn check_chunk(
+    formatter_name: &FormatterName,
+    formatter: &Formatter,
+    paths: &[PathBuf],
+) -> anyhow::Result<(FormatterName, Vec<PathBuf>)> {
+    if paths.is_empty() {
+        return Ok((formatter_name.clone(), vec![]));
+    }
+
+    // Map each scratch copy back to its original path and original contents. The copy lives
+    // right beside the original (rather than under a separate temp tree) so formatters that
+    // resolve their config by walking up from the file's location (prettier, rustfmt,
+    // clang-format, editorconfig...) see the same per-directory config they'd see for the real
+    // file. Its name ends in a nonce rather than the original extension, so an extension-based
+    // include glob (e.g. `*.rs`) doesn't match it; a catch-all glob such as `*` still does.
+    let mut copies = BTreeMap::new();
+    for path in paths {
+        let original_contents = fs::read(path)?;
+        let scratch_path = scratch_path_for(path, formatter_name);
+        fs::write(&scratch_path, &original_contents)?;
+        copies.insert(scratch_path, (path.clone(), original_contents));
+    }
+
+    let scratch_paths: Vec<PathBuf> = copies.keys().cloned().collect();
+    if let Err(err) = formatter.clone().fmt(&scratch_paths) {
+        CLOG.error(&format!("{} failed: {}", formatter, err));
+    }
+
+    let mut changed = vec![];
+    for (scratch_path, (original_path, original_contents)) in &copies {
+        if fs::read(scratch_path).unwrap_or_default() != *original_contents {
+            changed.push(original_path.clone());
+        }
+        fs::remove_file(scratch_path).ok();
+    }
+
+    Ok((formatter_name.clone(), changed))
+}
+
+/// A scratch copy path living beside `path`, namespaced by formatter and a nonce so two
+/// formatters (or two overlapping chunks) checking the same file never collide.
+fn scratch_path_for(path: &Path, formatter_name: &FormatterName) -> PathBuf {
+    let nonce = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let sanitized_formatter = formatter_name.to_string().replace(['/', '\\', ':'], "_");
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        ".{}.treefmt-check-{}-{}",
+        file_name, sanitized_formatter, nonce
+    ))
+}
@@ -0,0 +1,165 @@
+//! Formatter version fingerprinting is in this module, used to invalidate eval cache entries
+//! when a formatter binary is upgraded in place.
+
+use crate::formatter::{Formatter, FormatterName};
+use crate::CLOG;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A fingerprint identifying the exact version of a formatter binary.
+pub type Fingerprint = String;
+
+/// Compute a fingerprint for `formatter`.
+pub fn compute(formatter: &Formatter) -> Fingerprint {
+    for version_flag in ["--version", "-V", "-version"] {
+        if let Ok(output) = Command::new(&formatter.command).arg(version_flag).output() {
+            if output.status.success() {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let text = if stdout.trim().is_empty() {
+                    String::from_utf8_lossy(&output.stderr).into_owned()
+                } else {
+                    stdout.into_owned()
+                };
+                if !text.trim().is_empty() {
+                    return text.trim().to_string();
+                }
+            }
+        }
+    }
+
+    fingerprint_from_metadata(Path::new(&formatter.command))
+        .unwrap_or_else(|| format!("unknown:{}", formatter.command))
+}
+
+/// Combine the resolved executable's path, size and mtime into one string.
+fn fingerprint_from_metadata(executable: &Path) -> Option<String> {
+    let resolved = resolve_on_path(executable);
+    let meta = fs::metadata(&resolved).ok()?;
+    let mtime = meta.modified().ok()?;
+    Some(format!("{}:{}:{:?}", resolved.display(), meta.len(), mtime))
+}
+
+/// Resolve a bare command name (e.g. `rustfmt`) against `$PATH`; leave paths as-is.
+fn resolve_on_path(executable: &Path) -> PathBuf {
+    if executable.components().count() > 1 {
+        return executable.to_path_buf();
+    }
+    std::env::var_os("PATH")
+        .into_iter()
+        .flat_map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>())
+        .map(|dir| dir.join(executable))
+        .find(|candidate| candidate.is_file())
+        .unwrap_or_else(|| executable.to_path_buf())
+}
+
+/// Load the fingerprints recorded on the previous run, keyed by formatter name.
+///
+/// A line that doesn't parse (e.g. hand-edited or truncated by a crash) is skipped rather than
+/// aborting the whole load, so a single bad entry only costs that one formatter's cache, not
+/// every formatter's.
+pub fn load(cache_dir: &Path) -> BTreeMap<FormatterName, Fingerprint> {
+    fs::read_to_string(manifest_path(cache_dir))
+        .ok()
+        .map(|contents| {
+            contents
+                .lines()
+                .filter_map(|line| line.split_once('\t'))
+                .map(|(name, fp)| (FormatterName::from(name.to_string()), decode_line(fp)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Persist the current fingerprints so the next run can detect a formatter upgrade.
+pub fn write(cache_dir: &Path, fingerprints: &BTreeMap<FormatterName, Fingerprint>) {
+    let contents = fingerprints
+        .iter()
+        .map(|(name, fp)| format!("{}\t{}", name, encode_line(fp)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if let Err(err) = fs::write(manifest_path(cache_dir), contents) {
+        CLOG.warn(&format!("Couldn't write formatter fingerprints: {}", err));
+    }
+}
+
+fn manifest_path(cache_dir: &Path) -> PathBuf {
+    cache_dir.join("formatter-fingerprints")
+}
+
+/// Escape backslashes, tabs and newlines so a fingerprint (e.g. a multi-line `--version` banner)
+/// can't corrupt the tab/newline-delimited manifest format.
+fn encode_line(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Inverse of [`encode_line`].
+fn decode_line(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_roundtrips_multiline_version_banners() {
+        let banner = "rustfmt 1.7.0-stable (abc123 2024-01-01)\nbinary: rustfmt\ncommit-hash: abc123";
+        assert_eq!(decode_line(&encode_line(banner)), banner);
+    }
+
+    #[test]
+    fn encode_line_never_contains_a_tab_or_newline() {
+        let banner = "line one\twith a tab\nline two";
+        let encoded = encode_line(banner);
+        assert!(!encoded.contains('\t'));
+        assert!(!encoded.contains('\n'));
+    }
+
+    #[test]
+    fn load_skips_a_malformed_line_without_losing_the_rest() {
+        let dir = std::env::temp_dir().join(format!(
+            "treefmt-fingerprint-test-{:?}",
+            std::thread::current().id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            manifest_path(&dir),
+            format!("good-formatter\t{}\nthis line has no tab", encode_line("1.0.0")),
+        )
+        .unwrap();
+
+        let loaded = load(&dir);
+        assert_eq!(
+            loaded.get(&FormatterName::from("good-formatter".to_string())),
+            Some(&"1.0.0".to_string())
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,132 @@
+//! Git-aware helpers for the `--changed-since` incremental mode are in this module.
+
+use crate::CLOG;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Ask git for the set of files added or modified relative to `base_rev`, plus any untracked
+/// files.
+///
+/// If `base_rev` is `None`, the merge-base between `HEAD` and the repository's default branch
+/// is used instead. Returns `None` if `tree_root` is not a git checkout, or if the underlying
+/// git invocation fails, so that callers can fall back to a full walk.
+pub fn get_git_modified_files(
+    tree_root: &Path,
+    base_rev: Option<&str>,
+) -> Option<BTreeSet<PathBuf>> {
+    if !tree_root.join(".git").exists() {
+        return None;
+    }
+
+    let base_rev = match base_rev {
+        Some(rev) => rev.to_string(),
+        None => merge_base_with_default_branch(tree_root)?,
+    };
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(tree_root)
+        .args(["diff", "--name-only", "--diff-filter=ACMR", &base_rev])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        CLOG.warn(&format!(
+            "git diff against {} failed: {}",
+            base_rev,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+        return None;
+    }
+
+    let mut modified = parse_name_only(tree_root, &String::from_utf8_lossy(&output.stdout));
+
+    // `git diff` only ever considers tracked/staged content, so a brand-new file that was never
+    // `git add`ed wouldn't otherwise show up until it's staged. Fold in untracked files too, the
+    // same way `git status` would report them.
+    modified.extend(untracked_files(tree_root)?);
+
+    Some(modified)
+}
+
+/// List files git knows about but that aren't tracked, excluding anything `.gitignore`d.
+fn untracked_files(tree_root: &Path) -> Option<BTreeSet<PathBuf>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(tree_root)
+        .args(["ls-files", "--others", "--exclude-standard"])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        CLOG.warn(&format!(
+            "git ls-files --others failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+        return None;
+    }
+
+    Some(parse_name_only(tree_root, &String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Turn `git diff --name-only`'s output (repo-relative paths, one per line) into absolute paths
+/// rooted at `tree_root`.
+fn parse_name_only(tree_root: &Path, stdout: &str) -> BTreeSet<PathBuf> {
+    stdout
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| tree_root.join(line))
+        .collect()
+}
+
+/// Resolve the merge-base between `HEAD` and the first default-branch candidate that exists.
+fn merge_base_with_default_branch(tree_root: &Path) -> Option<String> {
+    for default_branch in ["origin/HEAD", "origin/main", "origin/master"] {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(tree_root)
+            .args(["merge-base", "HEAD", default_branch])
+            .output()
+            .ok()?;
+
+        if output.status.success() {
+            let rev = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if !rev.is_empty() {
+                return Some(rev);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_name_only_joins_relative_paths_onto_tree_root() {
+        let tree_root = Path::new("/repo");
+        let stdout = "src/main.rs\nCargo.toml\nsub/dir/file.txt\n";
+
+        let parsed = parse_name_only(tree_root, stdout);
+
+        assert_eq!(
+            parsed,
+            BTreeSet::from([
+                PathBuf::from("/repo/src/main.rs"),
+                PathBuf::from("/repo/Cargo.toml"),
+                PathBuf::from("/repo/sub/dir/file.txt"),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_name_only_ignores_trailing_blank_line() {
+        let tree_root = Path::new("/repo");
+        assert_eq!(
+            parse_name_only(tree_root, "src/main.rs\n\n"),
+            BTreeSet::from([PathBuf::from("/repo/src/main.rs")])
+        );
+    }
+}
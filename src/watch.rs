@@ -0,0 +1,176 @@
+//! The long-running `--watch` daemon is in this module. It keeps the eval cache resident in
+//! memory for the life of the process and only resolves matches for paths an fsmonitor event
+//! reports as dirty.
+
+use crate::engine::{
+    build_thread_pool, diff_matches, load_formatters_by_scope, match_path, run_formatters,
+};
+use crate::eval_cache::CacheManifest;
+use crate::fingerprint::{self, Fingerprint};
+use crate::formatter::{Formatter, FormatterName};
+use crate::{Mtime, CLOG};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How long to wait after the last filesystem event before running the formatters, so a burst of
+/// saves (e.g. an editor writing a swap file, then the real file) triggers a single run.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Hard ceiling on how long a steady trickle of sub-[`DEBOUNCE`] events can keep postponing a
+/// run, so dirty paths are still flushed periodically even if the filesystem never goes quiet.
+const MAX_BATCH_DELAY: Duration = Duration::from_secs(2);
+
+/// How often to flush the in-memory cache to disk, regardless of activity.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Watch `tree_root` and reformat files as they change, until the process is interrupted.
+pub fn watch(
+    tree_root: &Path,
+    cache_dir: &Path,
+    treefmt_toml: &Path,
+    jobs: usize,
+) -> anyhow::Result<()> {
+    // Resolved once up front, the same way `run_treefmt` resolves it for a one-shot run, so a
+    // file under a subproject with its own nested `treefmt.toml` is matched against that config's
+    // formatters instead of the root's for the life of this `--watch` process.
+    let (formatters_by_scope, all_formatters) = load_formatters_by_scope(tree_root, treefmt_toml)?;
+
+    let mut cache =
+        CacheManifest::load(cache_dir, treefmt_toml).update_formatters(all_formatters.clone());
+    let mut fingerprints = fingerprint::load(cache_dir);
+    // Built once and reused across every debounced batch, rather than on every single call to
+    // `run_formatters` as `--watch` would otherwise do.
+    let pool = build_thread_pool(jobs);
+
+    let (tx, rx) = channel();
+    let mut watcher: RecommendedWatcher = notify::recommended_watcher(tx)?;
+    watcher.watch(tree_root, RecursiveMode::Recursive)?;
+
+    CLOG.info(&format!("Watching {} for changes...", tree_root.display()));
+
+    let mut dirty: BTreeSet<PathBuf> = BTreeSet::new();
+    let mut batch_started: Option<Instant> = None;
+    let mut last_flush = Instant::now();
+
+    loop {
+        match rx.recv_timeout(DEBOUNCE) {
+            Ok(Ok(event)) => {
+                dirty.extend(event.paths);
+                batch_started.get_or_insert_with(Instant::now);
+                // Keep draining events until the debounce window is quiet, unless a steady
+                // trickle of events has been postponing this batch for too long already.
+                if batch_started.unwrap().elapsed() < MAX_BATCH_DELAY {
+                    continue;
+                }
+            }
+            Ok(Err(err)) => {
+                CLOG.warn(&format!("watch error: {}", err));
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                // Debounce window elapsed with no new events: process whatever is dirty.
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if !dirty.is_empty() {
+            let batch = std::mem::take(&mut dirty);
+            batch_started = None;
+            process_batch(
+                &formatters_by_scope,
+                &all_formatters,
+                &mut cache,
+                &mut fingerprints,
+                &batch,
+                &pool,
+            );
+        }
+
+        if last_flush.elapsed() >= FLUSH_INTERVAL {
+            flush(&cache, &fingerprints, cache_dir, treefmt_toml);
+            last_flush = Instant::now();
+        }
+    }
+
+    flush(&cache, &fingerprints, cache_dir, treefmt_toml);
+    Ok(())
+}
+
+/// Resolve and format a batch of dirty paths, updating the resident cache in place.
+fn process_batch(
+    formatters_by_scope: &BTreeMap<PathBuf, BTreeMap<FormatterName, Formatter>>,
+    all_formatters: &BTreeMap<FormatterName, Formatter>,
+    cache: &mut CacheManifest,
+    fingerprints: &mut BTreeMap<FormatterName, Fingerprint>,
+    dirty: &BTreeSet<PathBuf>,
+    pool: &rayon::ThreadPool,
+) {
+    // Lazily resolve only the dirty paths into matches. When nothing actually matches a
+    // formatter (e.g. a directory event, or a file outside every include), this stays empty and
+    // we skip straight past formatting and cache bookkeeping entirely.
+    let mut matches: BTreeMap<FormatterName, BTreeMap<PathBuf, Mtime>> = BTreeMap::new();
+    for path in dirty {
+        if !path.is_file() {
+            continue;
+        }
+        for (name, mtime) in match_path(formatters_by_scope, path) {
+            matches.entry(name).or_default().insert(path.clone(), mtime);
+        }
+    }
+
+    if matches.is_empty() {
+        return;
+    }
+
+    // A formatter whose version fingerprint changed since it was last computed (i.e. the binary
+    // was upgraded in place while watching) is treated as a full cache miss for this batch,
+    // regardless of what the mtime-keyed cache says -- mirroring `run_treefmt`'s one-shot
+    // behavior. Compare against the previously recorded fingerprint *before* overwriting it, or
+    // every batch would compare a fingerprint against itself and never detect an upgrade.
+    let mut stale_formatters: BTreeSet<FormatterName> = BTreeSet::new();
+    for name in matches.keys() {
+        if let Some(fmt) = all_formatters.get(name) {
+            let new_fingerprint = fingerprint::compute(fmt);
+            if fingerprints.get(name) != Some(&new_fingerprint) {
+                stale_formatters.insert(name.clone());
+            }
+            fingerprints.insert(name.clone(), new_fingerprint);
+        }
+    }
+
+    let filtered = cache.clone().filter_matches(matches.clone());
+    let filtered = filtered
+        .into_iter()
+        .map(|(name, paths)| {
+            if stale_formatters.contains(&name) {
+                (name.clone(), matches.get(&name).cloned().unwrap_or_default())
+            } else {
+                (name, paths)
+            }
+        })
+        .collect::<BTreeMap<FormatterName, BTreeMap<PathBuf, Mtime>>>();
+
+    let new_matches = run_formatters(all_formatters, &filtered, pool);
+    *cache = cache.clone().add_results(new_matches.clone());
+
+    let reformatted: usize = diff_matches(&filtered, &new_matches)
+        .values()
+        .map(|paths| paths.len())
+        .sum();
+    if reformatted > 0 {
+        CLOG.info(&format!("{} file(s) reformatted", reformatted));
+    }
+}
+
+fn flush(
+    cache: &CacheManifest,
+    fingerprints: &BTreeMap<FormatterName, Fingerprint>,
+    cache_dir: &Path,
+    treefmt_toml: &Path,
+) {
+    cache.clone().write(cache_dir, treefmt_toml);
+    fingerprint::write(cache_dir, fingerprints);
+}